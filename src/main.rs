@@ -2,17 +2,112 @@ use clap::{App, Arg};
 use chrono::{Local, Timelike};
 use std::fs::{File, OpenOptions, remove_file, symlink_metadata, read_link};
 use std::os::unix::fs;
-use std::io::{self, Write, Result, Read, BufRead};
+use std::io::{self, Write, Result, Read, BufRead, BufReader};
 use std::path::Path;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 use std::thread;
 use std::time::Duration;
 
+/// Compression algorithm applied to a log file once it's rotated out.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Compression {
+    Gzip,
+    Zstd,
+    Xz,
+}
+
+impl Compression {
+    /// File extension (without the leading dot) used for files compressed with this algorithm.
+    fn extension(&self) -> &'static str {
+        match self {
+            Compression::Gzip => "gz",
+            Compression::Zstd => "zst",
+            Compression::Xz => "xz",
+        }
+    }
+}
+
+impl std::str::FromStr for Compression {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "gzip" | "gz" => Ok(Compression::Gzip),
+            "zstd" | "zst" => Ok(Compression::Zstd),
+            "xz" => Ok(Compression::Xz),
+            other => Err(format!("Unrecognized compression algorithm: {}", other)),
+        }
+    }
+}
+
+/// Returns true if `path` already has one of the supported compression extensions.
+fn has_compressed_extension(path: &str) -> bool {
+    path.ends_with(".gz") || path.ends_with(".zst") || path.ends_with(".xz")
+}
+
+/// How often the log file is rotated.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Period {
+    Daily,
+    Hourly,
+    Weekly,
+}
+
+impl Period {
+    /// The `strftime` pattern used to build rotated filenames and to detect a period change.
+    fn format_pattern(&self) -> &'static str {
+        match self {
+            Period::Daily => "%Y-%m-%d",
+            Period::Hourly => "%Y-%m-%d.%H",
+            Period::Weekly => "%G-W%V",
+        }
+    }
+
+    /// Length, in characters, of the leading date part of the bucket this period embeds in a
+    /// rotated filename (for hourly rotation the `.%H` hour suffix is not included).
+    fn bucket_len(&self) -> usize {
+        match self {
+            Period::Daily | Period::Hourly => 10,
+            Period::Weekly => 8,
+        }
+    }
+
+    /// Parses a bucket (as sliced using `bucket_len`) back into the date it represents.
+    fn parse_bucket(&self, bucket: &str) -> Option<chrono::NaiveDate> {
+        match self {
+            Period::Daily | Period::Hourly => chrono::NaiveDate::parse_from_str(bucket, "%Y-%m-%d").ok(),
+            Period::Weekly => {
+                let (year_str, week_str) = bucket.split_once("-W")?;
+                let iso_year: i32 = year_str.parse().ok()?;
+                let iso_week: u32 = week_str.parse().ok()?;
+                chrono::NaiveDate::from_isoywd_opt(iso_year, iso_week, chrono::Weekday::Mon)
+            }
+        }
+    }
+}
+
+impl std::str::FromStr for Period {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "daily" => Ok(Period::Daily),
+            "hourly" => Ok(Period::Hourly),
+            "weekly" => Ok(Period::Weekly),
+            other => Err(format!("Unrecognized rotation period: {}", other)),
+        }
+    }
+}
+
 struct Config {
     folder: String,
     base_filename: String,
-    gzip_on_rotate: bool,
+    compression: Option<Compression>,
+    max_bytes: Option<u64>,
+    max_files: Option<usize>,
+    max_age_days: Option<u64>,
+    period: Period,
 }
 
 fn main() -> std::io::Result<()> {
@@ -40,29 +135,75 @@ fn main() -> std::io::Result<()> {
             Arg::with_name("compress")
                 .short('c')
                 .long("compress")
-                .value_name("COMPRESS")
-                .help("If supplied, indicates old log files should be compressed")
-                .takes_value(false),
+                .value_name("ALGORITHM")
+                .help("Compress old log files on rotation. Optionally specify an algorithm: gzip (default), zstd, or xz")
+                .takes_value(true)
+                .min_values(0)
+                .possible_values(["gzip", "zstd", "xz"]),
+        )
+        .arg(
+            Arg::with_name("max-size")
+                .short('s')
+                .long("max-size")
+                .value_name("SIZE")
+                .help("Rotate the log once it exceeds this size, even mid-day (accepts suffixes like 10M, 500K)")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("keep")
+                .short('k')
+                .long("keep")
+                .value_name("N")
+                .help("Keep only the N most recent rotated log files, deleting older ones")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("max-age")
+                .short('a')
+                .long("max-age")
+                .value_name("DAYS")
+                .help("Delete rotated log files older than this many days")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("period")
+                .short('p')
+                .long("period")
+                .value_name("PERIOD")
+                .help("How often to rotate the log file")
+                .takes_value(true)
+                .possible_values(["daily", "hourly", "weekly"])
+                .default_value("daily"),
         ).get_matches();
 
     let config = Config {
         folder: matches.value_of("directory").unwrap().to_string(),
         base_filename: matches.value_of("filename").unwrap().to_string(),
-        gzip_on_rotate: matches.is_present("compress"),
+        compression: if matches.is_present("compress") {
+            let algorithm = matches.value_of("compress").unwrap_or("gzip");
+            Some(algorithm.parse().expect("Invalid --compress algorithm"))
+        } else {
+            None
+        },
+        max_bytes: matches.value_of("max-size").map(|s| parse_size(s).expect("Invalid --max-size value")),
+        max_files: matches.value_of("keep").map(|s| s.parse().expect("Invalid --keep value")),
+        max_age_days: matches.value_of("max-age").map(|s| s.parse().expect("Invalid --max-age value")),
+        period: matches.value_of("period").unwrap().parse().expect("Invalid --period value"),
     };
 
-    // Used to signal when the day changes; if true then the writer should rotate
+    // Used to signal when the rotation period has rolled over; if true then the writer should rotate
     let date_changed = Arc::new(AtomicBool::new(true));
     let near_end_of_day = Arc::new(AtomicBool::new(true));
 
-    // Start a thread to monitor when the local date changes.
-    // When date does change, it sets date_changed to true.
+    // Start a thread to monitor when the current rotation period's bucket changes.
+    // When it does, it sets date_changed to true.
     {
         let date_changed_clone = date_changed.clone();
         let near_end_of_day_clone = near_end_of_day.clone();
+        let period = config.period;
 
         thread::spawn(move || {
-            let mut old_date = Local::now().date_naive();
+            let mut old_bucket = Local::now().format(period.format_pattern()).to_string();
 
             near_end_of_day_clone.store(true, Ordering::SeqCst);
 
@@ -75,10 +216,10 @@ fn main() -> std::io::Result<()> {
                     thread::sleep(Duration::from_secs(59));
                 }
 
-                let new_date = Local::now().date_naive();
-                if old_date != new_date {
+                let new_bucket = Local::now().format(period.format_pattern()).to_string();
+                if old_bucket != new_bucket {
                     date_changed_clone.store(true, Ordering::SeqCst);
-                    old_date = new_date;
+                    old_bucket = new_bucket;
                 }
             }
         });
@@ -108,7 +249,8 @@ fn main() -> std::io::Result<()> {
     let mut buffer = Vec::with_capacity(8192);
     buffer.resize(8192, 0);
 
-    let mut file = reopen_log_file(&config)?;
+    let mut file = reopen_log_file(&config, false)?;
+    let mut current_size: u64 = file.metadata()?.len();
 
     loop {
         if near_end_of_day.load(Ordering::Relaxed) {
@@ -124,18 +266,22 @@ fn main() -> std::io::Result<()> {
             break;
         }
 
-        // If we've been notified that the date has changed, rotate log files
+        // If we've been notified that the date has changed, or the file has grown
+        // past the configured size limit, rotate log files
         let has_date_changed = date_changed.load(Ordering::SeqCst);
+        let size_exceeded = config.max_bytes.is_some_and(|max| current_size >= max);
 
-        if has_date_changed {
+        if has_date_changed || size_exceeded {
             date_changed.store(false, Ordering::SeqCst);
 
             drop(file);
 
-            file = reopen_log_file(&config)?;
+            file = reopen_log_file(&config, size_exceeded)?;
+            current_size = 0;
         }
 
         file.write_all(&buffer)?;
+        current_size += buffer.len() as u64;
 
         // If we only read 1 byte, sleep to let stdin fill up
         if buffer.len() == 1 {
@@ -148,12 +294,13 @@ fn main() -> std::io::Result<()> {
 
 /// Opens the log file for the current date.
 /// N.B. limitation is this always creates a date-stamped file, whereas really what we want to do is only do that on rotate...
-fn reopen_log_file(config: &Config) -> Result<File> {
+/// `force_rotate` forces a new, disambiguated filename even when the period hasn't changed.
+fn reopen_log_file(config: &Config, force_rotate: bool) -> Result<File> {
     let date_format;
     if cfg!(debug_assertions) {
         date_format = "%Y-%m-%d.%H%M%S";
     } else {
-        date_format = "%Y-%m-%d";
+        date_format = config.period.format_pattern();
     }
 
     let folder = Path::new(&config.folder);
@@ -162,10 +309,27 @@ fn reopen_log_file(config: &Config) -> Result<File> {
 
     let formatted_date = Local::now().format(date_format).to_string();
     let filename = format!("{}-{}", config.base_filename, formatted_date);
-    let filepath = folder.join(&filename);
+    let mut filepath = folder.join(&filename);
+
+    // A forced rotation (e.g. size-triggered) can happen more than once within the same
+    // date-stamped period, so the filename above may already be taken by the file we're
+    // rotating away from. Disambiguate with an incrementing index in that case.
+    if force_rotate && filepath.exists() {
+        let mut index = 1;
+        loop {
+            let candidate_name = format!("{}.{}", filename, index);
+            let candidate_path = folder.join(&candidate_name);
+            if !candidate_path.exists() {
+                filepath = candidate_path;
+                break;
+            }
+            index += 1;
+        }
+    }
 
     // Remove existing link
     let should_relink;
+    let mut old_log_filepath_to_compress: Option<String> = None;
     if link.exists() {
         // Retrieve the metadata for the symlink
         let link_metadata = symlink_metadata(&link)?;
@@ -175,15 +339,10 @@ fn reopen_log_file(config: &Config) -> Result<File> {
             let target_path = read_link(&link)?.canonicalize()?;
             let old_log_filepath = target_path.as_os_str().to_str().unwrap();
 
-            should_relink = old_log_filepath != filepath.to_str().unwrap();
-
-            if should_relink && config.gzip_on_rotate && !old_log_filepath.ends_with(".gz") {
-                let old_log_file = old_log_filepath.to_owned();
+            should_relink = force_rotate || old_log_filepath != filepath.to_str().unwrap();
 
-                // Compress the old log file in the background
-                thread::spawn(move || {
-                    gzip_file_and_delete_original(&old_log_file);
-                });
+            if should_relink && config.compression.is_some() && !has_compressed_extension(old_log_filepath) {
+                old_log_filepath_to_compress = Some(old_log_filepath.to_owned());
             }
         } else {
             should_relink = true;
@@ -210,41 +369,174 @@ fn reopen_log_file(config: &Config) -> Result<File> {
         if let Err(e) = fs::symlink(&filepath, &link) {
             return Err(e);
         }
+
+        // Compress and enforce retention in the background, on the same thread
+        let folder = config.folder.clone();
+        let base_filename = config.base_filename.clone();
+        let compression = config.compression;
+        let max_files = config.max_files;
+        let max_age_days = config.max_age_days;
+        let period = config.period;
+
+        thread::spawn(move || {
+            if let (Some(old_log_file), Some(compression)) = (old_log_filepath_to_compress, compression) {
+                compress_file_and_delete_original(&old_log_file, compression);
+            }
+
+            if let Some(max_age_days) = max_age_days {
+                enforce_max_age(&folder, &base_filename, max_age_days, period);
+            }
+
+            if let Some(max_files) = max_files {
+                enforce_retention_limit(&folder, &base_filename, max_files);
+            }
+        });
     }
 
     Ok(file)
 }
 
+/// Deletes old rotated log files for `base_filename`, keeping only the `max_files` most recent.
+fn enforce_retention_limit(folder: &str, base_filename: &str, max_files: usize) {
+    let prefix = format!("{}-", base_filename);
+
+    let entries = match std::fs::read_dir(folder) {
+        Ok(entries) => entries,
+        Err(_) => return,
+    };
+
+    let mut rotated_files: Vec<(String, std::path::PathBuf)> = entries
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| {
+            let file_name = entry.file_name().into_string().ok()?;
+            if file_name.starts_with(&prefix) {
+                let date_portion = file_name[prefix.len()..].to_owned();
+                Some((date_portion, entry.path()))
+            } else {
+                None
+            }
+        })
+        .collect();
+
+    if rotated_files.len() <= max_files {
+        return;
+    }
+
+    // Order by the part after the prefix, ignoring any compression extension and comparing
+    // `.`-separated numeric parts as numbers, so that `D` < `D.1` < `D.2` < `D.10` for a period `D`.
+    rotated_files.sort_by_cached_key(|(date_portion, _)| {
+        let stem = [".gz", ".zst", ".xz"].iter()
+            .find_map(|ext| date_portion.strip_suffix(ext))
+            .unwrap_or(date_portion.as_str());
+        stem.split('.')
+            .map(|part| part.parse::<u64>().map_err(|_| part.to_owned()))
+            .collect::<Vec<_>>()
+    });
+
+    let excess = rotated_files.len() - max_files;
+    for (_, path) in rotated_files.into_iter().take(excess) {
+        if let Err(e) = remove_file(&path) {
+            if e.kind() != std::io::ErrorKind::NotFound {
+                eprintln!("Could not delete old log file {}: {}", path.display(), e);
+            }
+        }
+    }
+}
+
+/// Deletes rotated log files for `base_filename` older than `max_age_days`.
+fn enforce_max_age(folder: &str, base_filename: &str, max_age_days: u64, period: Period) {
+    let prefix = format!("{}-", base_filename);
+    let today = Local::now().date_naive();
+    let bucket_len = period.bucket_len();
+
+    let entries = match std::fs::read_dir(folder) {
+        Ok(entries) => entries,
+        Err(_) => return,
+    };
+
+    for entry in entries.filter_map(|e| e.ok()) {
+        let file_name = match entry.file_name().into_string() {
+            Ok(name) => name,
+            Err(_) => continue,
+        };
+
+        if !file_name.starts_with(&prefix) {
+            continue;
+        }
+
+        let date_portion = &file_name[prefix.len()..];
+        if date_portion.len() < bucket_len {
+            continue;
+        }
+
+        let file_date = match period.parse_bucket(&date_portion[..bucket_len]) {
+            Some(date) => date,
+            None => continue,
+        };
+
+        if (today - file_date).num_days() > max_age_days as i64 {
+            if let Err(e) = remove_file(entry.path()) {
+                if e.kind() != std::io::ErrorKind::NotFound {
+                    eprintln!("Could not delete expired log file {}: {}", entry.path().display(), e);
+                }
+            }
+        }
+    }
+}
 
-fn gzip_file_and_delete_original(file_path: &str) {
+fn compress_file_and_delete_original(file_path: &str, compression: Compression) {
     if is_file_empty(file_path) {
         // Empty file, just delete; do not display errors if we cannot delete
         let _ = remove_file(file_path);
     } else {
-        let gz_file_path = format!("{}.gz", file_path);
+        let compressed_file_path = format!("{}.{}", file_path, compression.extension());
 
-        let result = try_gzip_file(file_path, &gz_file_path);
+        let result = match compression {
+            Compression::Gzip => try_gzip_file(file_path, &compressed_file_path),
+            Compression::Zstd => try_zstd_file(file_path, &compressed_file_path),
+            Compression::Xz => try_xz_file(file_path, &compressed_file_path),
+        };
 
         match result {
             Ok(_) => {
                 if let Err(e) = remove_file(file_path) {
                     if Path::new(&file_path).exists() {
-                        eprintln!("GZipped old log file after rotate, could not delete original: {}", e);
+                        eprintln!("Compressed old log file after rotate, could not delete original: {}", e);
                     }
                 }
             }
             Err(e) => {
-                // Remove the .gz file if there was an error.
-                if let Ok(_) = remove_file(&gz_file_path) {
-                    eprintln!("Error gzipping old log file after rotate: {}", e);
-                } else if Path::new(&gz_file_path).exists() {
-                    eprintln!("Error gzipping old log file after rotate and unable to delete partial .gz file: {}", e);
+                // Remove the compressed file if there was an error.
+                if let Ok(_) = remove_file(&compressed_file_path) {
+                    eprintln!("Error compressing old log file after rotate: {}", e);
+                } else if Path::new(&compressed_file_path).exists() {
+                    eprintln!("Error compressing old log file after rotate and unable to delete partial output file: {}", e);
                 }
             }
         }
     }
 }
 
+/// Parses a byte size like `10M` or `500K` (or a bare number of bytes) into a raw byte count.
+fn parse_size(value: &str) -> std::result::Result<u64, String> {
+    let value = value.trim();
+
+    let (number, multiplier) = match value.chars().last() {
+        Some(c) if c.is_ascii_alphabetic() => {
+            let multiplier = match c.to_ascii_uppercase() {
+                'K' => 1024,
+                'M' => 1024 * 1024,
+                'G' => 1024 * 1024 * 1024,
+                _ => return Err(format!("Unrecognized size suffix: {}", c)),
+            };
+            (&value[..value.len() - 1], multiplier)
+        }
+        _ => (value, 1),
+    };
+
+    number.trim().parse::<u64>().map(|n| n * multiplier).map_err(|e| e.to_string())
+}
+
 fn is_file_empty(file_path: &str) -> bool {
     use std::fs;
 
@@ -258,17 +550,42 @@ fn try_gzip_file(src_file_path: &str, gz_file_path: &String) -> io::Result<()> {
     use flate2::write::GzEncoder;
     use flate2::Compression;
 
-    let mut input_file = File::open(src_file_path)?;
-    let mut output_file = File::create(&gz_file_path)?;
+    let input_file = File::open(src_file_path)?;
+    let output_file = File::create(&gz_file_path)?;
 
-    let mut encoder = GzEncoder::new(&mut output_file, Compression::default());
-    let mut buffer = Vec::new();
-    input_file.read_to_end(&mut buffer)?;
+    let mut reader = BufReader::new(input_file);
+    let mut encoder = GzEncoder::new(output_file, Compression::default());
 
-    // Write the buffer to the encoder and finish the encoding process.
-    // If there's an error during the gzip process, it will be propagated.
-    match encoder.write_all(&buffer).and(encoder.finish()) {
-        Ok(_) => Ok(()),
-        Err(e) => Err(e),
-    }
+    io::copy(&mut reader, &mut encoder)?;
+    encoder.finish()?;
+
+    Ok(())
+}
+
+fn try_zstd_file(src_file_path: &str, zst_file_path: &String) -> io::Result<()> {
+    let input_file = File::open(src_file_path)?;
+    let output_file = File::create(zst_file_path)?;
+
+    let mut reader = BufReader::new(input_file);
+    let mut encoder = zstd::stream::write::Encoder::new(output_file, 0)?;
+
+    io::copy(&mut reader, &mut encoder)?;
+    encoder.finish()?;
+
+    Ok(())
+}
+
+fn try_xz_file(src_file_path: &str, xz_file_path: &String) -> io::Result<()> {
+    use xz2::write::XzEncoder;
+
+    let input_file = File::open(src_file_path)?;
+    let output_file = File::create(xz_file_path)?;
+
+    let mut reader = BufReader::new(input_file);
+    let mut encoder = XzEncoder::new(output_file, 6);
+
+    io::copy(&mut reader, &mut encoder)?;
+    encoder.finish()?;
+
+    Ok(())
 }